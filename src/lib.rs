@@ -9,12 +9,23 @@
 //! `insert` updates provided key in the data store with the provided value.
 //!
 //! The thread safety property of the `Library` is the result of wrapping multiple concurrency
-//! primitives; Arc, ArcCell, and Mutex.
+//! primitives; Arc, ArcSwap, and Mutex.
 //!
 //! Mutex and Arc are part of the standard library. We use mutex to prevent multiple writers from
 //! adding a new key simultaneously. Note: this is subtly, but significantly, different from
 //! preventing multiple insertions of the same key.
 //!
+//! ## Sharding
+//!
+//! Internally, `Library` is not a single `HashMap` behind a single lock. Instead, following the
+//! approach used by crates like `dashmap`, the keyspace is split across a fixed number of
+//! `Shard`s (a power of two, defaulting to the next power of two at or above `num_cpus::get()`).
+//! Each shard owns its own `ArcSwap<LibraryStore<K, V>>` and its own `insert_mutex`, and a key is
+//! routed to exactly one shard by hashing it once (with a `Library`-wide `BuildHasher`) and taking
+//! the low bits of the hash. This means a structural insert (a new key) only has to clone and swap
+//! the one shard's map rather than the whole data set, and two writers landing in different shards
+//! never contend with one another.
+//!
 //! ## Insertion
 //!
 //! There are two different scenarios to consider: inserting a new value under a new key and
@@ -22,9 +33,10 @@
 //!
 //! ### New value under new key
 //!
-//! Inserting a value with a new key requires allocating additional space in the `HashMap` and
-//! potentially rearranging the underlying data. To prevent consistency errors the `Library` has an
-//! internal `Mutex` (`Library.insert_mutex`) which must be obtained before inserting a key.
+//! Inserting a value with a new key requires allocating additional space in the shard's `HashMap`
+//! and potentially rearranging the underlying data. To prevent consistency errors each `Shard` has
+//! an internal `Mutex` (`Shard.insert_mutex`) which must be obtained before inserting a key into
+//! that shard.
 //!
 //! ```
 //! use concurrent_hashmap::Library;
@@ -41,8 +53,8 @@
 //! ### New value under existing key
 //!
 //! Since the key already exists the `HashMap` does not need to allocate any additional storage (we
-//! are just swapping the contents of an ArcCell). So we can short-circuit the insertion process,
-//! and thus skipping lock acquisition, by providing a reference to the ArcCell and swapping directly.
+//! are just swapping the contents of an `ArcSwap`). So we can short-circuit the insertion process,
+//! and thus skipping lock acquisition, by providing a reference to the cell and swapping directly.
 //!
 //! This tradeoff for performance is what introduces the "Last Writer Wins" behavior for multiple
 //! insertions to the same key.
@@ -65,15 +77,11 @@
 //! assert_eq!(val123, Some(123.into()));
 //! ```
 //!
-//! ## `ArcCell`
-//!
-//! [`ArcCell`](https://github.com/aturon/crossbeam/blob/master/src/sync/arc_cell.rs) is provided by
-//! the crossbeam crate. The naming and documentation are atrocious, so we attempt to provide an
-//! explaination here.
+//! ## `ArcSwap`
 //!
-//! As the figure below attempts to depict, The defining feature of this type is the ability to swap
-//! out the contents of the heap allocated value (i.e. `Cell`) atomically. So a more accurate name
-//! would be `AtomicCell`.
+//! [`ArcSwap`](https://docs.rs/arc-swap) is the one concurrency primitive both the shard store
+//! (`Shard.internal_data`) and the per-key value cells are built on. The defining feature of this
+//! type is the ability to swap out the contents of the heap allocated value atomically.
 //!
 //! ```text
 //!          A ----> N
@@ -91,37 +99,190 @@
 //! see: https://internals.rust-lang.org/t/atomic-arc-swap/3588
 //! ```
 //!
+//! `update` below needs a compare-and-swap primitive, which is why value cells moved off
+//! crossbeam's `ArcCell` onto `ArcSwap` in the first place; the shard store followed suit so that
+//! `LibraryCache` (see "Per-thread read cache" below) could use `arc_swap::cache::Cache`'s cheap
+//! revalidation, which `ArcCell` has no equivalent for.
+//!
+//! ## Atomic updates
+//!
+//! Plain `insert` is "Last Writer Wins": two concurrent inserts to the same key race, and one
+//! silently clobbers the other. That's fine for replacing a value wholesale, but it can't express
+//! "add 1 to the counter under this key" correctly under contention.
+//!
+//! `update` fixes this with a CAS retry loop: it loads the current `Arc<V>` behind the key's
+//! `ArcSwap`, runs the caller's closure over it, and tries to `compare_and_swap` the result in only
+//! if nobody else has swapped the cell out from under it in the meantime. On a lost race it reloads
+//! and retries.
+//!
+//! ```
+//! use concurrent_hashmap::Library;
+//!
+//! let lib: Library<String, i64> = Library::new();
+//! lib.insert("counter".into(), 0);
+//! lib.update("counter".into(), |current| current.map_or(0, |v| v + 1));
+//! lib.update("counter".into(), |current| current.map_or(0, |v| v + 1));
+//! assert_eq!(lib.get("counter"), Some(2.into()));
+//! ```
+//!
+//! ## Bounded cache mode
+//!
+//! `Library::with_cost_capacity` turns a `Library` into a capacity-bounded cache, modeled on the
+//! Ristretto/Stretto design: a TinyLFU frequency estimator (see the `sketch` module) decides
+//! whether a newly inserted item is worth evicting an existing one for, so the map self-manages
+//! its footprint instead of growing without bound.
+//!
+//! ```
+//! use concurrent_hashmap::Library;
+//!
+//! let lib: Library<String, Vec<u8>> = Library::with_cost_capacity(16, |v: &Vec<u8>| v.len() as i64);
+//! lib.insert("a".into(), vec![0; 8]);
+//! lib.insert("b".into(), vec![0; 8]);
+//! assert_eq!(lib.get("a").map(|v| v.len()), Some(8));
+//! assert_eq!(lib.get("b").map(|v| v.len()), Some(8));
+//! ```
+//!
+//! ## Per-thread read cache
+//!
+//! `Library::cache` returns a `LibraryCache` handle, wrapping the relevant shard's store in an
+//! `arc_swap::cache::Cache`: a thread that repeatedly reads the same hot key(s) can hold one and
+//! revalidate with a single relaxed pointer load on every call instead of a full `ArcSwap::load`,
+//! only paying for the full load when the shard snapshot has actually changed. The value itself is
+//! always read fresh, so results never go stale.
+//!
+//! ```
+//! use concurrent_hashmap::Library;
+//!
+//! let lib: Library<String, i64> = Library::new();
+//! lib.insert("hot".into(), 1);
+//! let mut cache = lib.cache();
+//! assert_eq!(cache.get("hot"), Some(1.into()));
+//! assert_eq!(cache.get("hot"), Some(1.into()));
+//! ```
+//!
+//! ## Snapshot iteration
+//!
+//! `Library::snapshot` captures a consistent, point-in-time `Snapshot` of every shard (one cheap
+//! `Arc` clone each) that `iter`, `keys`, `values`, `len`, and `is_empty` then read from. Because
+//! the snapshot is just a handful of captured `Arc`s, later inserts into the live `Library` are
+//! never observed by it.
+//!
+//! ```
+//! use concurrent_hashmap::Library;
+//!
+//! let lib: Library<String, i64> = Library::new();
+//! lib.insert("a".into(), 1);
+//! lib.insert("b".into(), 2);
+//! let snapshot = lib.snapshot();
+//! lib.insert("c".into(), 3);
+//! assert_eq!(snapshot.len(), 2);
+//! assert_eq!(snapshot.values().map(|v| *v).sum::<i64>(), 3);
+//! ```
+//!
+//! Gated behind the `rayon` feature (as in dashmap), `&Snapshot` implements
+//! `rayon::iter::IntoParallelIterator`, so a large snapshot can be folded, filtered, or collected
+//! across threads.
+//!
 //! ## Caveats
 //!
 //! It is up to the user of `Library` to ensure that only a single update to an individual key happens concurrently.
 //! Otherwise the `Library` will default to the "Last Writer Wins" conflict resolution strategy (hardly ever the
-//! desired behavior from an end user perspective).
+//! desired behavior from an end user perspective). Callers that need correct read-modify-write semantics should use
+//! `update` instead of `insert`.
 
-extern crate crossbeam;
+extern crate num_cpus;
+extern crate arc_swap;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+
+mod sketch;
 
 use std::collections::hash_map::HashMap;
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicI64, Ordering};
 use std::borrow::Borrow;
 use std::clone::Clone;
-use crossbeam::sync::ArcCell;
+use std::marker::PhantomData;
+use std::hash::{BuildHasher, Hasher};
+use std::collections::hash_map::RandomState;
+use arc_swap::ArcSwap;
+use sketch::TinyLfuSketch;
+
+/// Default width (number of counters per row) for the Count-Min Sketch used by bounded-cache mode.
+const DEFAULT_SKETCH_WIDTH: usize = 4096;
+
+/// Number of recorded sightings between sketch aging passes, in bounded-cache mode.
+const DEFAULT_SAMPLE_SIZE: usize = 10 * DEFAULT_SKETCH_WIDTH;
+
+/// Number of existing keys sampled as eviction candidates when bounded-cache mode needs to make
+/// room for a newly admitted item.
+const EVICTION_SAMPLE_SIZE: usize = 5;
 
-pub type LibraryStore<K, V> = HashMap<K, Arc<ArcCell<V>>>;
+pub type LibraryStore<K, V> = HashMap<K, Arc<ArcSwap<V>>>;
 
 pub trait LibraryKey: ::std::cmp::Eq + ::std::hash::Hash + ::std::clone::Clone {}
 impl<              K: ::std::cmp::Eq + ::std::hash::Hash + ::std::clone::Clone> LibraryKey for K {}
 
-pub struct Library<K, V> where K: LibraryKey {
-    internal_data: ArcCell<LibraryStore<K, V>>,
+/// A single slice of the keyspace.
+///
+/// Each shard owns an independently swappable store and an independent insert lock, so
+/// structural writes (new keys) to two different shards never block one another.
+struct Shard<K, V> where K: LibraryKey {
+    internal_data: ArcSwap<LibraryStore<K, V>>,
     insert_mutex: Mutex<()>,
+    /// Running cost total for bounded-cache mode, tracked per-shard since `admit` only ever
+    /// samples eviction victims from the shard a new key lands in (see `Library::admit`). Unused,
+    /// and always `0`, outside of bounded-cache mode.
+    current_cost: AtomicI64,
 }
 
-impl<K, V> ::std::default::Default for Library<K, V> where K: LibraryKey {
-    fn default() -> Library<K, V> {
-        Library {
-            internal_data: ArcCell::new(HashMap::new().into()),
+impl<K, V> Shard<K, V> where K: LibraryKey {
+    fn with_capacity(capacity: usize) -> Self {
+        Shard {
+            internal_data: ArcSwap::new(HashMap::with_capacity(capacity).into()),
             insert_mutex: Mutex::new(()),
+            current_cost: AtomicI64::new(0),
         }
     }
+
+    #[inline]
+    fn internal_data(&self) -> Arc<LibraryStore<K, V>> {
+        self.internal_data.load()
+    }
+}
+
+pub struct Library<K, V> where K: LibraryKey {
+    shards: Vec<Shard<K, V>>,
+    shard_mask: usize,
+    hash_builder: RandomState,
+    cost_bound: Option<CostBound<V>>,
+}
+
+/// Bookkeeping for `Library::with_cost_capacity`'s bounded cache mode: the per-shard cost budget,
+/// the cost function, and the TinyLFU frequency estimator used to decide what gets evicted.
+///
+/// `max_cost` here is already the *per-shard* share of the budget passed to `with_cost_capacity`
+/// (see `Library::with_shards_and_cost_capacity`); the running total it's compared against lives
+/// on each `Shard` rather than here, since eviction is always local to one shard.
+struct CostBound<V> {
+    max_cost: i64,
+    cost_fn: Box<dyn Fn(&V) -> i64 + Send + Sync>,
+    sketch: Mutex<TinyLfuSketch>,
+}
+
+/// Rounds `n` up to the next power of two, with a floor of `1`.
+fn next_power_of_two(n: usize) -> usize {
+    if n == 0 {
+        1
+    } else {
+        n.next_power_of_two()
+    }
+}
+
+impl<K, V> ::std::default::Default for Library<K, V> where K: LibraryKey {
+    fn default() -> Library<K, V> {
+        Library::with_shards(next_power_of_two(num_cpus::get()))
+    }
 }
 
 impl<K, V> Library<K, V> where K: LibraryKey {
@@ -130,42 +291,167 @@ impl<K, V> Library<K, V> where K: LibraryKey {
     }
 
     pub fn with_capacity(capacity: usize) -> Self {
+        let num_shards = next_power_of_two(num_cpus::get());
+        Self::with_shards_and_capacity(num_shards, capacity)
+    }
+
+    /// Builds a `Library` with an explicit number of shards.
+    ///
+    /// `num_shards` is rounded up to the next power of two so that shard selection can be done
+    /// with a cheap bitmask rather than a modulo.
+    pub fn with_shards(num_shards: usize) -> Self {
+        Self::with_shards_and_capacity(num_shards, 0)
+    }
+
+    fn with_shards_and_capacity(num_shards: usize, capacity: usize) -> Self {
+        let num_shards = next_power_of_two(num_shards);
+        let per_shard_capacity = (capacity + num_shards - 1) / num_shards;
+        let shards = (0..num_shards)
+            .map(|_| Shard::with_capacity(per_shard_capacity))
+            .collect();
+
         Library {
-            internal_data: ArcCell::new(HashMap::with_capacity(capacity).into()),
-            insert_mutex: Mutex::new(()),
+            shards: shards,
+            shard_mask: num_shards - 1,
+            hash_builder: RandomState::new(),
+            cost_bound: None,
+        }
+    }
+
+    /// Builds a capacity-bounded `Library` that behaves like a cache: `max_cost` is split evenly
+    /// across the shards (see `with_shards_and_cost_capacity`), and once the cost of a shard's
+    /// entries (as measured by `cost_fn`) would exceed that shard's share, inserting a new key into
+    /// that shard requires evicting an existing one from it. The budget is not pooled across
+    /// shards, so an insert can be rejected while the total cost of all entries is below `max_cost`.
+    ///
+    /// Eviction is decided by a TinyLFU-style admission policy: a Count-Min Sketch (gated by a
+    /// doorkeeper Bloom filter so first sightings don't inflate estimates) tracks each key's
+    /// estimated access frequency. When room is needed, a handful of existing keys are sampled and
+    /// the one with the lowest estimated frequency is evicted, but only if the newcomer's own
+    /// estimated frequency is higher — otherwise the insert is rejected and the existing entries
+    /// are left alone. This tends toward keeping frequently-accessed items resident even as the
+    /// working set churns.
+    ///
+    /// Uses the same default shard count as `Library::new`. See `with_shards_and_cost_capacity` if
+    /// you need to pick the shard count explicitly.
+    pub fn with_cost_capacity<F>(max_cost: i64, cost_fn: F) -> Self
+    where F: Fn(&V) -> i64 + Send + Sync + 'static {
+        Self::with_shards_and_cost_capacity(next_power_of_two(num_cpus::get()), max_cost, cost_fn)
+    }
+
+    /// Builds a capacity-bounded `Library` (see `with_cost_capacity`) with an explicit number of
+    /// shards.
+    ///
+    /// Eviction only ever samples victims from the shard a new key lands in (see `Library::admit`),
+    /// so the cost budget has to be tracked per-shard rather than globally: `max_cost` is split
+    /// evenly across `num_shards` shards (with a floor of `1`, so a `max_cost` smaller than the
+    /// shard count doesn't zero out every shard's budget and reject every insert outright).
+    pub fn with_shards_and_cost_capacity<F>(num_shards: usize, max_cost: i64, cost_fn: F) -> Self
+    where F: Fn(&V) -> i64 + Send + Sync + 'static {
+        let num_shards = next_power_of_two(num_shards);
+        let per_shard_max_cost = (max_cost / num_shards as i64).max(1);
+
+        let cost_bound = Some(CostBound {
+            max_cost: per_shard_max_cost,
+            cost_fn: Box::new(cost_fn),
+            sketch: Mutex::new(TinyLfuSketch::new(DEFAULT_SKETCH_WIDTH, DEFAULT_SAMPLE_SIZE)),
+        });
+
+        Library {
+            cost_bound: cost_bound,
+            ..Self::with_shards(num_shards)
         }
     }
 
     #[inline]
-    fn internal_data(&self) -> Arc<LibraryStore<K, V>> {
-        self.internal_data.get()
+    fn hash<Q: ?Sized>(&self, key: &Q) -> u64
+    where K: Borrow<Q>, Q: ::std::hash::Hash + Eq {
+        let mut hasher = self.hash_builder.build_hasher();
+        key.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[inline]
+    fn shard_index<Q: ?Sized>(&self, key: &Q) -> usize
+    where K: Borrow<Q>, Q: ::std::hash::Hash + Eq {
+        (self.hash(key) as usize) & self.shard_mask
+    }
+
+    #[inline]
+    fn shard_for<Q: ?Sized>(&self, key: &Q) -> &Shard<K, V>
+    where K: Borrow<Q>, Q: ::std::hash::Hash + Eq {
+        &self.shards[self.shard_index(key)]
+    }
+
+    /// Returns a per-thread caching handle for cheap repeated reads of the same key(s).
+    ///
+    /// `get` always does a full `ArcSwap::load` on the shard's store, which costs an atomic load
+    /// plus an `Arc` refcount bump. `LibraryCache` wraps the shard store in an
+    /// `arc_swap::cache::Cache`, which revalidates with a single relaxed pointer load and only
+    /// pays for the full `load` (and the refcount bump that comes with it) when the shard has
+    /// actually been swapped out from under it since the last call. The queried key's value is
+    /// always freshly loaded from its own `ArcSwap` cell, so it's never stale.
+    pub fn cache(&self) -> LibraryCache<'_, K, V> {
+        LibraryCache {
+            library: self,
+            shard_index: 0,
+            cache: arc_swap::cache::Cache::new(&self.shards[0].internal_data),
+        }
+    }
+
+    /// Captures a consistent, point-in-time view of every key and value currently in the map.
+    ///
+    /// Since each shard's store is an atomically-swappable `Arc`, this is cheap: it just clones
+    /// one `Arc` per shard (no copying of keys or values) while other threads keep inserting and
+    /// updating. The returned `Snapshot` reflects the store at capture time and won't observe
+    /// later inserts — this falls directly out of the same Arc-swap model the rest of `Library`
+    /// already uses.
+    pub fn snapshot(&self) -> Snapshot<K, V> {
+        Snapshot {
+            shards: self.shards.iter().map(|shard| shard.internal_data()).collect(),
+        }
     }
 
     pub fn get<Q: ?Sized>(&self, key: &Q) -> Option<Arc<V>>
     where K: Borrow<Q>, Q: ::std::hash::Hash + Eq {
-        self.internal_data().get(key).map(|el| el.get())
+        if let Some(ref cost_bound) = self.cost_bound {
+            cost_bound.sketch.lock().unwrap().record(key);
+        }
+
+        self.shard_for(key).internal_data().get(key).map(|el| el.load())
     }
 
     pub fn insert(&self, key: K, value: V) {
-        let store = self.internal_data();
+        let shard = self.shard_for(&key);
+        let store = shard.internal_data();
 
         // short circuit if domain already exists
-        if let Some(arccell) = store.get(&key) {
-                arccell.set(value.into());
+        if let Some(arcswap) = store.get(&key) {
+                self.adjust_cost_for_replace(shard, arcswap, &value);
+                arcswap.store(value.into());
                 return;
         }
 
         // obtain lock (released at end of function scope)
-        let _guard = self.insert_mutex.lock().unwrap();
+        let _guard = shard.insert_mutex.lock().unwrap();
 
-        let store = self.internal_data();
+        let store = shard.internal_data();
 
         // exact copy of first `if let`
-        if let Some(arccell) = store.get(&key) {
-                arccell.set(value.into());
+        if let Some(arcswap) = store.get(&key) {
+                self.adjust_cost_for_replace(shard, arcswap, &value);
+                arcswap.store(value.into());
                 return;
         }
 
+        let mut evict: Vec<K> = Vec::new();
+        if let Some(ref cost_bound) = self.cost_bound {
+            match self.admit(shard, cost_bound, &store, &key, &value) {
+                Some(victims) => evict = victims,
+                None => return, // newcomer isn't hot enough to be worth evicting for
+            }
+        }
+
         let new_hash: LibraryStore<K, V> = {
             // Multiple bindings because rust is incapable of inferring types
             let new_hash: &LibraryStore<K, V> = store.borrow();
@@ -190,13 +476,291 @@ impl<K, V> Library<K, V> where K: LibraryKey {
             //     new_hash.insert(...);
             //
             let mut new_hash = new_hash.clone();
+            for victim in evict {
+                new_hash.remove(&victim);
+            }
             new_hash.insert(key,
-                            ArcCell::new(value.into()).into());
+                            ArcSwap::new(value.into()).into());
 
             new_hash
         };
 
-        self.internal_data.set(new_hash.into());
+        shard.internal_data.store(new_hash.into());
+    }
+
+    /// In bounded-cache mode, keeps the shard's `current_cost` correct when an existing key's
+    /// value is replaced in place (no eviction needed, since the key already has room reserved
+    /// for it).
+    fn adjust_cost_for_replace(&self, shard: &Shard<K, V>, arcswap: &ArcSwap<V>, new_value: &V) {
+        if self.cost_bound.is_some() {
+            self.adjust_cost_for_value_change(shard, &arcswap.load(), new_value);
+        }
+    }
+
+    /// In bounded-cache mode, keeps the shard's `current_cost` correct when a key's value changes
+    /// from `old_value` to `new_value` without any eviction (an in-place replace, or a successful
+    /// `update`).
+    fn adjust_cost_for_value_change(&self, shard: &Shard<K, V>, old_value: &V, new_value: &V) {
+        if let Some(ref cost_bound) = self.cost_bound {
+            let old_cost = (cost_bound.cost_fn)(old_value);
+            let new_cost = (cost_bound.cost_fn)(new_value);
+            shard.current_cost.fetch_add(new_cost - old_cost, Ordering::SeqCst);
+        }
+    }
+
+    /// Bounded-cache admission check for a brand new key, run while holding the shard's
+    /// `insert_mutex`. Returns the keys that should be evicted to make room for `value`
+    /// (possibly empty, if `value` already fits), or `None` if `value` isn't "hot" enough relative
+    /// to the sampled victims to be worth admitting at all.
+    ///
+    /// Both the cost budget and the sampled victims are scoped to `shard`/`store` (the shard the
+    /// new key hashes to): eviction never looks outside that one shard, so the budget being
+    /// checked here must be that shard's own `current_cost`/`max_cost`, not a library-wide total.
+    ///
+    /// `shard.current_cost` is only touched once, after admission is fully decided: the loop below
+    /// tracks candidate evictions (and the cost they'd free) in `evicted`/`freed_cost` without
+    /// mutating the shard's running total, so a later victim failing the frequency comparison and
+    /// returning `None` doesn't leave an earlier victim's cost permanently (and wrongly) subtracted.
+    fn admit(&self, shard: &Shard<K, V>, cost_bound: &CostBound<V>, store: &LibraryStore<K, V>, key: &K, value: &V) -> Option<Vec<K>> {
+        let new_cost = (cost_bound.cost_fn)(value);
+        let candidate_freq = cost_bound.sketch.lock().unwrap().record(key);
+
+        let mut evicted: Vec<K> = Vec::new();
+        let mut freed_cost: i64 = 0;
+
+        while shard.current_cost.load(Ordering::SeqCst) - freed_cost + new_cost > cost_bound.max_cost {
+            let sketch = cost_bound.sketch.lock().unwrap();
+            let victim = store.keys()
+                .filter(|candidate| !evicted.contains(candidate))
+                .take(EVICTION_SAMPLE_SIZE)
+                .min_by_key(|candidate| sketch.estimate_of(*candidate))
+                .cloned();
+            drop(sketch);
+
+            // nothing left to sample; can't make room
+            let victim = victim?;
+
+            let victim_freq = cost_bound.sketch.lock().unwrap().estimate_of(&victim);
+            if candidate_freq <= victim_freq {
+                return None;
+            }
+
+            let victim_cost = store.get(&victim).map_or(0, |cell| (cost_bound.cost_fn)(&cell.load()));
+            freed_cost += victim_cost;
+            evicted.push(victim);
+        }
+
+        shard.current_cost.fetch_add(new_cost - freed_cost, Ordering::SeqCst);
+        Some(evicted)
+    }
+
+    /// Atomically updates the value stored under `key`, correctly handling concurrent writers.
+    ///
+    /// Unlike `insert`, which is "Last Writer Wins", `update` loads the current value (or `None`
+    /// if the key is missing), hands it to `f` to compute a replacement, and uses
+    /// `ArcSwap::compare_and_swap` to install it only if no other thread swapped the cell in the
+    /// meantime. On a lost race it reloads the latest value and retries `f` against it.
+    ///
+    /// If the key doesn't exist yet, `update` falls back to the locked insert path (the same one
+    /// `insert` uses for new keys), re-checking for the key after acquiring the shard's
+    /// `insert_mutex` in case another thread inserted it first.
+    ///
+    /// In bounded-cache mode, a successful CAS swap on an existing key adjusts `current_cost` the
+    /// same way `insert` does for an in-place replace, and the new-key fallback runs the value
+    /// through the same `admit` admission check `insert` uses — so `update` can reject a new key
+    /// that isn't "hot" enough, just like `insert` does.
+    pub fn update<F>(&self, key: K, mut f: F)
+    where F: FnMut(Option<&V>) -> V {
+        let shard = self.shard_for(&key);
+        let store = shard.internal_data();
+
+        if let Some(arcswap) = store.get(&key) {
+            loop {
+                let current = arcswap.load();
+                let next: Arc<V> = f(Some(&current)).into();
+                let previous = arcswap.compare_and_swap(&current, next.clone());
+                if arc_swap::ptr_eq(&previous, &current) {
+                    self.adjust_cost_for_value_change(shard, &current, &next);
+                    return;
+                }
+            }
+        }
+
+        // obtain lock (released at end of function scope)
+        let _guard = shard.insert_mutex.lock().unwrap();
+
+        let store = shard.internal_data();
+
+        // re-check now that we hold the lock: another thread may have inserted the key first
+        if let Some(arcswap) = store.get(&key) {
+            loop {
+                let current = arcswap.load();
+                let next: Arc<V> = f(Some(&current)).into();
+                let previous = arcswap.compare_and_swap(&current, next.clone());
+                if arc_swap::ptr_eq(&previous, &current) {
+                    self.adjust_cost_for_value_change(shard, &current, &next);
+                    return;
+                }
+            }
+        }
+
+        let value = f(None);
+
+        let mut evict: Vec<K> = Vec::new();
+        if let Some(ref cost_bound) = self.cost_bound {
+            match self.admit(shard, cost_bound, &store, &key, &value) {
+                Some(victims) => evict = victims,
+                None => return, // newcomer isn't hot enough to be worth admitting
+            }
+        }
+
+        let new_hash: LibraryStore<K, V> = {
+            let new_hash: &LibraryStore<K, V> = store.borrow();
+            let mut new_hash = new_hash.clone();
+            for victim in evict {
+                new_hash.remove(&victim);
+            }
+            new_hash.insert(key, ArcSwap::new(value.into()).into());
+            new_hash
+        };
+
+        shard.internal_data.store(new_hash.into());
+    }
+}
+
+type ShardStoreCache<'a, K, V> = arc_swap::cache::Cache<&'a ArcSwap<LibraryStore<K, V>>, Arc<LibraryStore<K, V>>>;
+
+/// A per-thread caching handle into a `Library`, returned by `Library::cache`.
+///
+/// See `Library::cache` for the performance rationale. Each thread that reads the same key(s)
+/// repeatedly should hold its own `LibraryCache` rather than sharing one, since there's no
+/// synchronization between the cache and concurrent writers beyond the staleness check `get`
+/// already performs on every call.
+///
+/// The shard-level store snapshot is held in an `arc_swap::cache::Cache`, which revalidates it
+/// with a single relaxed pointer load on every call rather than a full `ArcSwap::load` — that's
+/// the actual atomic traffic this handle cuts down on. `get` always re-reads the queried key's
+/// value straight out of that snapshot, so it never returns a value that's gone stale due to a
+/// subsequent `insert` or `update` — including CAS `update` results, which a value-level cache
+/// would otherwise hide until some unrelated structural change in the same shard forced a reload.
+pub struct LibraryCache<'a, K, V> where K: LibraryKey + 'a, V: 'a {
+    library: &'a Library<K, V>,
+    shard_index: usize,
+    cache: ShardStoreCache<'a, K, V>,
+}
+
+impl<'a, K, V> LibraryCache<'a, K, V> where K: LibraryKey {
+    /// Looks up `key`, reusing the cached shard store snapshot when nothing structural has
+    /// changed in this shard, but always freshly loading the value itself.
+    pub fn get<Q: ?Sized>(&mut self, key: &Q) -> Option<Arc<V>>
+    where K: Borrow<Q>, Q: ::std::hash::Hash + Eq {
+        let shard_index = self.library.shard_index(key);
+        if shard_index != self.shard_index {
+            self.shard_index = shard_index;
+            self.cache = arc_swap::cache::Cache::new(&self.library.shards[shard_index].internal_data);
+        }
+
+        self.cache.load().get(key).map(|cell| cell.load())
+    }
+}
+
+/// A consistent, point-in-time view over a `Library`'s contents, returned by `Library::snapshot`.
+///
+/// Internally this just holds the one `Arc<LibraryStore<K, V>>` captured from each shard at
+/// snapshot time, so taking a snapshot is O(num_shards), not O(n). Iterating it only ever walks
+/// those captured maps, so later inserts into the live `Library` are invisible to it.
+pub struct Snapshot<K, V> where K: LibraryKey {
+    shards: Vec<Arc<LibraryStore<K, V>>>,
+}
+
+/// Iterator over a `Snapshot`'s `(key, value)` pairs. See `Snapshot::iter`.
+pub struct Iter<'a, K, V> where K: LibraryKey {
+    inner: Box<dyn Iterator<Item = (&'a K, Arc<V>)> + 'a>,
+}
+
+/// Iterator over a `Snapshot`'s keys. See `Snapshot::keys`.
+pub struct Keys<'a, K, V> where K: LibraryKey {
+    inner: Box<dyn Iterator<Item = &'a K> + 'a>,
+    _value: PhantomData<V>,
+}
+
+/// Iterator over a `Snapshot`'s values. See `Snapshot::values`.
+pub struct Values<'a, K, V> where K: LibraryKey {
+    inner: Box<dyn Iterator<Item = Arc<V>> + 'a>,
+    _key: PhantomData<&'a K>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> where K: LibraryKey {
+    type Item = (&'a K, Arc<V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+impl<'a, K, V> Iterator for Keys<'a, K, V> where K: LibraryKey {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+impl<'a, K, V> Iterator for Values<'a, K, V> where K: LibraryKey {
+    type Item = Arc<V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+impl<K, V> Snapshot<K, V> where K: LibraryKey {
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            inner: Box::new(self.shards.iter()
+                .flat_map(|shard| shard.iter().map(|(key, cell)| (key, cell.load())))),
+        }
+    }
+
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys {
+            inner: Box::new(self.shards.iter().flat_map(|shard| shard.keys())),
+            _value: PhantomData,
+        }
+    }
+
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values {
+            inner: Box::new(self.shards.iter()
+                .flat_map(|shard| shard.values().map(|cell| cell.load()))),
+            _key: PhantomData,
+        }
+    }
+}
+
+/// Parallel traversal of a `Snapshot`, gated behind the `rayon` feature as in dashmap: the
+/// shards' captured maps are chained into one sequential iterator, and `ParallelBridge` hands its
+/// items out to rayon's work-stealing pool to fold/filter/collect.
+#[cfg(feature = "rayon")]
+impl<'a, K, V> rayon::iter::IntoParallelIterator for &'a Snapshot<K, V>
+where K: LibraryKey + Send + Sync, V: Send + Sync {
+    type Item = (&'a K, Arc<V>);
+    type Iter = rayon::iter::IterBridge<Box<dyn Iterator<Item = (&'a K, Arc<V>)> + Send + 'a>>;
+
+    fn into_par_iter(self) -> Self::Iter {
+        use rayon::iter::ParallelBridge;
+        let sequential: Box<dyn Iterator<Item = (&'a K, Arc<V>)> + Send + 'a> = Box::new(
+            self.shards.iter().flat_map(|shard| shard.iter().map(|(key, cell)| (key, cell.load()))),
+        );
+        sequential.par_bridge()
     }
 }
 
@@ -239,4 +803,225 @@ mod tests {
         assert_eq!(val0, 12345.into());
         assert_eq!(val1, 67890.into());
     }
+
+    #[test]
+    fn with_shards_rounds_up_to_power_of_two() {
+        let lib: Library<String, i64> = Library::with_shards(5);
+        assert_eq!(lib.shard_mask, 7);
+    }
+
+    #[test]
+    fn update_missing_key_falls_back_to_insert() {
+        let lib: Library<String, i64> = Library::new();
+        lib.update("counter".into(), |current| current.map_or(0, |v| v + 1));
+        assert_eq!(lib.get("counter"), Some(0.into()));
+    }
+
+    #[test]
+    fn update_existing_key_is_read_modify_write() {
+        let lib: Library<String, i64> = Library::new();
+        lib.insert("counter".into(), 41);
+        lib.update("counter".into(), |current| current.map_or(0, |v| v + 1));
+        assert_eq!(lib.get("counter"), Some(42.into()));
+    }
+
+    #[test]
+    fn keys_in_different_shards_do_not_collide() {
+        let lib: Library<String, i64> = Library::with_shards(4);
+        for i in 0..100 {
+            lib.insert(format!("key-{}", i), i);
+        }
+        for i in 0..100 {
+            assert_eq!(lib.get(&format!("key-{}", i)), Some(Arc::new(i)));
+        }
+    }
+
+    #[test]
+    fn cost_capacity_admits_while_under_budget() {
+        // Pinned to a single shard: the budget is split per-shard (see
+        // `with_shards_and_cost_capacity`), so letting this default to `num_cpus`-many shards
+        // would make whether 5 keys costing 10 each fit under a budget of 100 depend on how many
+        // cores the test happens to run on.
+        let lib: Library<String, i64> = Library::with_shards_and_cost_capacity(1, 100, |_| 10);
+        for i in 0..5 {
+            lib.insert(format!("key-{}", i), i);
+        }
+        for i in 0..5 {
+            assert_eq!(lib.get(&format!("key-{}", i)), Some(i.into()));
+        }
+    }
+
+    #[test]
+    fn cost_capacity_rejects_cold_newcomer_over_hot_residents() {
+        // Pinned to a single shard so "hot" and "cold" are guaranteed to be co-resident; admission
+        // only ever samples victims from the newcomer's own shard, so with the default (possibly
+        // many) shards this could pass for the wrong reason (no victim found in "cold"'s shard at
+        // all, rather than the frequency comparison actually rejecting it).
+        let lib: Library<String, i64> = Library::with_shards_and_cost_capacity(1, 10, |_| 10);
+        lib.insert("hot".into(), 1);
+        for _ in 0..50 {
+            lib.get("hot");
+        }
+        lib.insert("cold".into(), 2);
+        assert_eq!(lib.get("hot"), Some(1.into()));
+        assert_eq!(lib.get("cold"), None);
+    }
+
+    #[test]
+    fn cost_capacity_single_shard_hot_newcomer_evicts_cold_resident() {
+        let lib: Library<String, i64> = Library::with_shards_and_cost_capacity(1, 10, |_| 10);
+        lib.insert("cold".into(), 1);
+        for _ in 0..50 {
+            lib.get("hot");
+        }
+        lib.insert("hot".into(), 2);
+        assert_eq!(lib.get("cold"), None);
+        assert_eq!(lib.get("hot"), Some(2.into()));
+    }
+
+    #[test]
+    fn update_on_missing_key_is_rejected_when_not_hot_enough() {
+        let lib: Library<String, i64> = Library::with_shards_and_cost_capacity(1, 10, |_| 10);
+        lib.insert("hot".into(), 1);
+        for _ in 0..50 {
+            lib.get("hot");
+        }
+        lib.update("cold".into(), |_| 2);
+        assert_eq!(lib.get("cold"), None);
+        assert_eq!(lib.get("hot"), Some(1.into()));
+    }
+
+    #[test]
+    fn update_on_existing_key_keeps_cost_accounting_correct() {
+        let lib: Library<String, i64> = Library::with_shards_and_cost_capacity(1, 10, |v| *v);
+        lib.insert("a".into(), 10);
+        // Shrinks "a"'s cost from 10 to 4, freeing 6 worth of budget. If `update` failed to
+        // adjust the shard's running cost total, the stale total of 10 would make the insert
+        // below think no room is left and reject it.
+        lib.update("a".into(), |_| 4);
+        lib.insert("b".into(), 5);
+        assert_eq!(lib.get("a"), Some(4.into()));
+        assert_eq!(lib.get("b"), Some(5.into()));
+    }
+
+    #[test]
+    fn admit_does_not_leak_cost_accounting_on_partial_eviction_then_rejection() {
+        let lib: Library<String, i64> = Library::with_shards_and_cost_capacity(1, 10, |v| *v);
+
+        // Two residents at the budget limit (cost 5 each): "v1" stays cold (frequency ~0), "v2" is
+        // read until its estimated frequency saturates.
+        lib.insert("v1".into(), 5);
+        lib.insert("v2".into(), 5);
+        for _ in 0..50 {
+            lib.get("v2");
+        }
+
+        // A few reads give "candidate" a moderate frequency: high enough to beat cold "v1" but
+        // nowhere near saturated "v2". Its cost (8) needs both residents evicted to fit, so
+        // admission samples "v1" first (lowest frequency), provisionally evicts it, then samples
+        // "v2" and rejects the whole admission since "candidate" isn't hot enough to beat it.
+        for _ in 0..5 {
+            lib.get("candidate");
+        }
+        lib.insert("candidate".into(), 8);
+        assert_eq!(lib.get("candidate"), None);
+        assert_eq!(lib.get("v1"), Some(5.into()));
+        assert_eq!(lib.get("v2"), Some(5.into()));
+
+        // If the rejected admission above had left "v1"'s cost permanently subtracted from the
+        // shard's running total (the bug), the shard would now believe it has 5 worth of headroom
+        // it doesn't actually have, and this cost-5 hot insert would be admitted without evicting
+        // anything — leaving "v1", "v2", and "filler" all resident at once, 15 worth of cost
+        // against a budget of 10. With accounting kept correct, "v1" (still the coldest resident)
+        // is the one evicted to make room instead.
+        for _ in 0..50 {
+            lib.get("filler");
+        }
+        lib.insert("filler".into(), 5);
+        assert_eq!(lib.get("v1"), None);
+        assert_eq!(lib.get("v2"), Some(5.into()));
+        assert_eq!(lib.get("filler"), Some(5.into()));
+    }
+
+    #[test]
+    fn cache_returns_current_value_for_hot_key() {
+        let lib: Library<String, i64> = Library::new();
+        lib.insert("hot".into(), 1);
+        let mut cache = lib.cache();
+        assert_eq!(cache.get("hot"), Some(1.into()));
+        assert_eq!(cache.get("hot"), Some(1.into()));
+    }
+
+    #[test]
+    fn cache_observes_structural_inserts_of_the_queried_key() {
+        let lib: Library<String, i64> = Library::new();
+        let mut cache = lib.cache();
+        assert_eq!(cache.get("hot"), None);
+        lib.insert("hot".into(), 1);
+        assert_eq!(cache.get("hot"), Some(1.into()));
+    }
+
+    #[test]
+    fn cache_observes_in_place_value_replacement() {
+        let lib: Library<String, i64> = Library::new();
+        lib.insert("hot".into(), 1);
+        let mut cache = lib.cache();
+        assert_eq!(cache.get("hot"), Some(1.into()));
+        lib.insert("hot".into(), 2);
+        assert_eq!(cache.get("hot"), Some(2.into()));
+    }
+
+    #[test]
+    fn cache_observes_updates_to_an_existing_key() {
+        let lib: Library<String, i64> = Library::new();
+        lib.insert("hot".into(), 1);
+        let mut cache = lib.cache();
+        assert_eq!(cache.get("hot"), Some(1.into()));
+        lib.update("hot".into(), |current| current.map_or(0, |v| v + 1));
+        assert_eq!(cache.get("hot"), Some(2.into()));
+    }
+
+    #[test]
+    fn snapshot_reflects_contents_at_capture_time() {
+        let lib: Library<String, i64> = Library::new();
+        lib.insert("a".into(), 1);
+        lib.insert("b".into(), 2);
+
+        let snapshot = lib.snapshot();
+        lib.insert("c".into(), 3);
+
+        assert_eq!(snapshot.len(), 2);
+        assert!(!snapshot.is_empty());
+
+        let mut values: Vec<i64> = snapshot.values().map(|v| *v).collect();
+        values.sort();
+        assert_eq!(values, vec![1, 2]);
+
+        let mut keys: Vec<&String> = snapshot.keys().collect();
+        keys.sort();
+        assert_eq!(keys, vec![&String::from("a"), &String::from("b")]);
+    }
+
+    #[test]
+    fn empty_snapshot_is_empty() {
+        let lib: Library<String, i64> = Library::new();
+        let snapshot = lib.snapshot();
+        assert_eq!(snapshot.len(), 0);
+        assert!(snapshot.is_empty());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn snapshot_parallel_iteration_visits_every_entry() {
+        use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+        let lib: Library<String, i64> = Library::new();
+        for i in 0..100 {
+            lib.insert(i.to_string(), i);
+        }
+
+        let snapshot = lib.snapshot();
+        let sum: i64 = (&snapshot).into_par_iter().map(|(_, v)| *v).sum();
+        assert_eq!(sum, (0..100).sum());
+    }
 }
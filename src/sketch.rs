@@ -0,0 +1,176 @@
+//! A TinyLFU frequency estimator: a Count-Min Sketch of 4-bit saturating counters, gated by a
+//! "doorkeeper" Bloom filter, used by `Library`'s bounded cache mode to decide whether a newly
+//! inserted item is "hot" enough to be worth evicting something else for.
+
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::collections::hash_map::RandomState;
+
+const DEPTH: usize = 4;
+
+/// Counts accesses per key, aging out old activity over time.
+///
+/// A key's very first sighting only marks it in the doorkeeper Bloom filter; only subsequent
+/// sightings increment the Count-Min Sketch. This keeps one-off accesses from inflating a key's
+/// estimated frequency. Every `sample_size` recorded sightings, the doorkeeper is cleared and
+/// every counter is halved so that stale activity fades rather than accumulating forever.
+pub struct TinyLfuSketch {
+    width_mask: usize,
+    counters: Vec<u8>,
+    doorkeeper: Vec<u64>,
+    doorkeeper_mask: usize,
+    hash_builder: RandomState,
+    samples: usize,
+    sample_size: usize,
+}
+
+impl TinyLfuSketch {
+    pub fn new(width: usize, sample_size: usize) -> Self {
+        let width = width.next_power_of_two().max(16);
+        let doorkeeper_bits = (width * DEPTH).next_power_of_two();
+
+        TinyLfuSketch {
+            width_mask: width - 1,
+            counters: vec![0u8; (width * DEPTH + 1) / 2],
+            doorkeeper: vec![0u64; doorkeeper_bits / 64],
+            doorkeeper_mask: doorkeeper_bits - 1,
+            hash_builder: RandomState::new(),
+            samples: 0,
+            sample_size: sample_size,
+        }
+    }
+
+    /// Records a sighting of `key` and returns its estimated access frequency afterwards.
+    pub fn record<T: Hash + ?Sized>(&mut self, key: &T) -> u8 {
+        let hash = self.hash_of(key);
+        self.age_if_due();
+
+        if !self.doorkeeper_contains(hash) {
+            self.set_doorkeeper(hash);
+        } else {
+            for row in 0..DEPTH {
+                let slot = self.counter_slot(row, self.row_index(hash, row));
+                self.increment_counter(slot);
+            }
+        }
+
+        self.estimate(hash)
+    }
+
+    /// Returns `key`'s estimated access frequency without recording a new sighting.
+    pub fn estimate_of<T: Hash + ?Sized>(&self, key: &T) -> u8 {
+        self.estimate(self.hash_of(key))
+    }
+
+    fn hash_of<T: Hash + ?Sized>(&self, key: &T) -> u64 {
+        let mut hasher = self.hash_builder.build_hasher();
+        key.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn estimate(&self, hash: u64) -> u8 {
+        (0..DEPTH)
+            .map(|row| self.get_counter(self.counter_slot(row, self.row_index(hash, row))))
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Mixes the row number into `hash` so each of the `DEPTH` rows samples a different slice of
+    /// the hash space, the same way a Count-Min Sketch would use `DEPTH` independent hashes.
+    fn row_index(&self, hash: u64, row: usize) -> usize {
+        let mixed = hash ^ (row as u64).wrapping_mul(0x9E3779B97F4A7C15);
+        (mixed as usize) & self.width_mask
+    }
+
+    fn counter_slot(&self, row: usize, col: usize) -> usize {
+        row * (self.width_mask + 1) + col
+    }
+
+    fn get_counter(&self, slot: usize) -> u8 {
+        let byte = self.counters[slot / 2];
+        if slot % 2 == 0 { byte & 0x0F } else { byte >> 4 }
+    }
+
+    fn increment_counter(&mut self, slot: usize) {
+        let byte_index = slot / 2;
+        let byte = self.counters[byte_index];
+        if slot % 2 == 0 {
+            let value = byte & 0x0F;
+            if value < 0x0F {
+                self.counters[byte_index] = (byte & 0xF0) | (value + 1);
+            }
+        } else {
+            let value = byte >> 4;
+            if value < 0x0F {
+                self.counters[byte_index] = (byte & 0x0F) | ((value + 1) << 4);
+            }
+        }
+    }
+
+    fn doorkeeper_bits(&self, hash: u64) -> (usize, usize) {
+        let a = (hash as usize) & self.doorkeeper_mask;
+        let b = ((hash >> 32) as usize) & self.doorkeeper_mask;
+        (a, b)
+    }
+
+    fn doorkeeper_contains(&self, hash: u64) -> bool {
+        let (a, b) = self.doorkeeper_bits(hash);
+        self.bit_is_set(a) && self.bit_is_set(b)
+    }
+
+    fn set_doorkeeper(&mut self, hash: u64) {
+        let (a, b) = self.doorkeeper_bits(hash);
+        self.doorkeeper[a / 64] |= 1 << (a % 64);
+        self.doorkeeper[b / 64] |= 1 << (b % 64);
+    }
+
+    fn bit_is_set(&self, bit: usize) -> bool {
+        (self.doorkeeper[bit / 64] & (1 << (bit % 64))) != 0
+    }
+
+    fn age_if_due(&mut self) {
+        self.samples += 1;
+        if self.samples < self.sample_size {
+            return;
+        }
+        self.samples = 0;
+
+        for byte in self.counters.iter_mut() {
+            let high = (*byte >> 4) >> 1;
+            let low = (*byte & 0x0F) >> 1;
+            *byte = (high << 4) | low;
+        }
+        for word in self.doorkeeper.iter_mut() {
+            *word = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_sighting_does_not_raise_estimate() {
+        let mut sketch = TinyLfuSketch::new(64, 10_000);
+        assert_eq!(sketch.record(&"a"), 0);
+    }
+
+    #[test]
+    fn repeated_sightings_raise_estimate() {
+        let mut sketch = TinyLfuSketch::new(64, 10_000);
+        sketch.record(&"a");
+        sketch.record(&"a");
+        assert!(sketch.estimate_of(&"a") >= 1);
+    }
+
+    #[test]
+    fn aging_halves_counters() {
+        let mut sketch = TinyLfuSketch::new(64, 4);
+        sketch.record(&"a");
+        sketch.record(&"a");
+        let before_aging = sketch.estimate_of(&"a");
+        sketch.record(&"a");
+        sketch.record(&"a");
+        assert!(sketch.estimate_of(&"a") <= before_aging);
+    }
+}